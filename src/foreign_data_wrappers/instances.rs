@@ -0,0 +1,95 @@
+use crate::instance::get_instances;
+use pg_extend::{
+    pg_datum,
+    pg_fdw::{ForeignData, ForeignRow, OptionMap},
+    pg_type,
+};
+use pg_extern_attr::pg_foreignwrapper;
+use wasmer::Extern;
+
+struct Row {
+    instance_id: String,
+    wasm_file: String,
+    exported_functions: i64,
+    exported_memories: i64,
+}
+
+#[pg_foreignwrapper]
+struct InstancesForeignDataWrapper {
+    inner: Vec<Row>,
+}
+
+impl Iterator for InstancesForeignDataWrapper {
+    type Item = Box<dyn ForeignRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.pop() {
+            Some(row) => Some(Box::new(InstanceForeignDataWrapper { inner: row })),
+            None => None,
+        }
+    }
+}
+
+impl ForeignData for InstancesForeignDataWrapper {
+    fn begin(_sopts: OptionMap, _topts: OptionMap, _table_name: String) -> Self {
+        InstancesForeignDataWrapper {
+            inner: get_instances()
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(instance_id, instance_info)| {
+                    let (exported_functions, exported_memories) = instance_info
+                        .instance
+                        .exports
+                        .iter()
+                        .fold((0, 0), |(functions, memories), (_, export)| match export {
+                            Extern::Function(_) => (functions + 1, memories),
+                            Extern::Memory(_) => (functions, memories + 1),
+                            _ => (functions, memories),
+                        });
+
+                    Row {
+                        instance_id: instance_id.clone(),
+                        wasm_file: instance_info.wasm_file.clone(),
+                        exported_functions,
+                        exported_memories,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn schema(
+        _server_opts: OptionMap,
+        server_name: String,
+        _remote_schema: String,
+        local_schema: String,
+    ) -> Option<Vec<String>> {
+        Some(vec![format!(
+            "CREATE FOREIGN TABLE {schema}.instances (instance_id text, wasm_file text, exported_functions int8, exported_memories int8) SERVER {server}",
+            server = server_name,
+            schema = local_schema
+        )])
+    }
+}
+
+struct InstanceForeignDataWrapper {
+    inner: Row,
+}
+
+impl ForeignRow for InstanceForeignDataWrapper {
+    fn get_field(
+        &self,
+        name: &str,
+        _typ: pg_type::PgType,
+        _opts: OptionMap,
+    ) -> Result<Option<pg_datum::PgDatum>, &str> {
+        match name {
+            "instance_id" => Ok(Some(self.inner.instance_id.clone().into())),
+            "wasm_file" => Ok(Some(self.inner.wasm_file.clone().into())),
+            "exported_functions" => Ok(Some(self.inner.exported_functions.into())),
+            "exported_memories" => Ok(Some(self.inner.exported_memories.into())),
+            _ => Err("Unknown field"),
+        }
+    }
+}
@@ -1,8 +1,413 @@
-use pg_extend::{debug, error};
+use pg_extend::{debug, pg_spi};
 use pg_extern_attr::pg_extern;
 use std::{collections::HashMap, fs::File, io::prelude::*, sync::RwLock};
 use uuid::Uuid;
-use wasmer::{imports, Instance, Module, Store, Type, Value};
+use wasmer::{Instance, Module, Store, Type, Value};
+
+mod host {
+    //! Host functions ("imports") exposed to guest WASM modules under the
+    //! `"pg"` namespace, so a module can call back into Postgres instead of
+    //! only ever being called by it.
+    //!
+    //! ABI:
+    //!
+    //! - `pg.log(ptr: i32, len: i32)`: reads `len` bytes starting at `ptr`
+    //!   out of the instance's exported memory, interprets them as UTF-8,
+    //!   and logs the string through [`debug!`].
+    //! - `pg.query_scalar(ptr: i32, len: i32) -> i64`: same string decoding,
+    //!   then runs the string as a read-only SQL statement through SPI and
+    //!   returns its first column, first row as an `i64` (`0` if the query
+    //!   produced no rows or a NULL, or if it was rejected as not read-only).
+    //!
+    //!   "Read-only" here is only a syntactic guard on the statement's
+    //!   leading keyword (see [`is_probably_read_only`]) - `pg_spi::get_one`
+    //!   itself has no read-only execution mode to delegate to, so a
+    //!   `SELECT` that calls a volatile, writing function would still slip
+    //!   through. Tighten this if/when SPI exposes real read-only execution.
+    //!
+    //! Both functions require the instance to export its linear memory as
+    //! `memory`, which `wasmer`'s `WasmerEnv` derive wires up automatically.
+
+    use super::{debug, pg_spi};
+    use pg_extend::error;
+    use wasmer::{Function, ImportObject, LazyInit, Memory, Store, WasmerEnv};
+
+    #[derive(WasmerEnv, Clone)]
+    struct Env {
+        #[wasmer(export)]
+        memory: LazyInit<Memory>,
+    }
+
+    fn read_guest_string(memory: &Memory, ptr: i32, len: i32) -> String {
+        let view = memory.view::<u8>();
+        let bytes: Vec<u8> = view[ptr as usize..(ptr as usize + len as usize)]
+            .iter()
+            .map(|cell| cell.get())
+            .collect();
+
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    fn pg_log(env: &Env, ptr: i32, len: i32) {
+        let memory = env.memory_ref().expect("`memory` export not initialized");
+        debug!("{}", read_guest_string(memory, ptr, len));
+    }
+
+    /// Best-effort check that `query` looks like a read-only statement,
+    /// judged solely by its leading keyword. This is not a real guarantee -
+    /// see the `pg.query_scalar` ABI note above - just a guard against the
+    /// obviously-wrong case of a guest sneaking in an `INSERT`/`UPDATE`/
+    /// `DELETE`/DDL statement.
+    fn is_probably_read_only(query: &str) -> bool {
+        let leading_keyword: String = query
+            .trim_start()
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect::<String>()
+            .to_ascii_uppercase();
+
+        matches!(
+            leading_keyword.as_str(),
+            "SELECT" | "WITH" | "VALUES" | "TABLE" | "EXPLAIN" | "SHOW"
+        )
+    }
+
+    fn pg_query_scalar(env: &Env, ptr: i32, len: i32) -> i64 {
+        let memory = env.memory_ref().expect("`memory` export not initialized");
+        let query = read_guest_string(memory, ptr, len);
+
+        if !is_probably_read_only(&query) {
+            error!(
+                "guest attempted a non-read-only SPI query `{}`; only SELECT/WITH/VALUES/TABLE/EXPLAIN/SHOW statements are allowed",
+                query
+            );
+            return 0;
+        }
+
+        match pg_spi::get_one::<i64>(&query) {
+            Ok(Some(value)) => value,
+            Ok(None) => 0,
+            Err(e) => {
+                error!("error running SPI query `{}` from guest: {}", query, e);
+                0
+            }
+        }
+    }
+
+    pub(super) fn import_object(store: &Store) -> ImportObject {
+        let env = Env {
+            memory: LazyInit::new(),
+        };
+
+        wasmer::imports! {
+            "pg" => {
+                "log" => Function::new_native_with_env(store, env.clone(), pg_log),
+                "query_scalar" => Function::new_native_with_env(store, env, pg_query_scalar),
+            }
+        }
+    }
+}
+
+mod error {
+    //! Errors raised by instance/function lifecycle and invocation failures.
+    //!
+    //! Every variant carries the SQLSTATE a SQL caller should see, following
+    //! the standard Postgres error-code table: unknown instances/exports map
+    //! to `undefined_function` (42883), argument-count and type mismatches
+    //! to `invalid_parameter_value` (22023), module compilation/instantiation
+    //! failures to `external_routine_exception` (38000), and runtime traps to
+    //! `external_routine_invocation_exception` (39000). [`raise`] reports the
+    //! error straight through Postgres' own `ereport` machinery, so a failing
+    //! `SELECT invoke_function_*(...)` aborts the statement transactionally
+    //! instead of the caller silently getting back NULL.
+
+    use pg_extend::pg_sys;
+    use std::fmt;
+
+    pub(super) enum WasmError {
+        FileOpen {
+            wasm_file: String,
+            source: std::io::Error,
+        },
+        FileRead {
+            wasm_file: String,
+            source: std::io::Error,
+        },
+        Compile {
+            wasm_file: String,
+            source: String,
+        },
+        Instantiate {
+            wasm_file: String,
+            source: String,
+        },
+        InstanceNotFound {
+            instance_id: String,
+        },
+        ExportNotFound {
+            instance_id: String,
+            name: String,
+            source: String,
+        },
+        ArityMismatch {
+            instance_id: String,
+            function_name: String,
+        },
+        TypeMismatch {
+            instance_id: String,
+            function_name: String,
+            reason: String,
+        },
+        Trap {
+            instance_id: String,
+            function_name: String,
+            source: String,
+        },
+        MemoryOutOfBounds {
+            instance_id: String,
+            function_name: String,
+            ptr: i32,
+            len: i32,
+        },
+    }
+
+    impl fmt::Display for WasmError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                WasmError::FileOpen { wasm_file, source } => {
+                    write!(f, "error opening {} - {}", wasm_file, source)
+                }
+                WasmError::FileRead { wasm_file, source } => {
+                    write!(f, "error reading {} - {}", wasm_file, source)
+                }
+                WasmError::Compile { wasm_file, source } => {
+                    write!(f, "error compiling module from {} - {}", wasm_file, source)
+                }
+                WasmError::Instantiate { wasm_file, source } => write!(
+                    f,
+                    "error instantiating instance from {} - {}",
+                    wasm_file, source
+                ),
+                WasmError::InstanceNotFound { instance_id } => {
+                    write!(f, "Instance with ID `{}` isn't found.", instance_id)
+                }
+                WasmError::ExportNotFound {
+                    instance_id,
+                    name,
+                    source,
+                } => write!(
+                    f,
+                    "Exported function `{}` does not exist in instance `{}`: {}",
+                    name, instance_id, source
+                ),
+                WasmError::ArityMismatch {
+                    instance_id,
+                    function_name,
+                } => write!(
+                    f,
+                    "Failed to call the `{}` exported function of instance `{}`: Invalid number of arguments.",
+                    function_name, instance_id
+                ),
+                WasmError::TypeMismatch {
+                    instance_id,
+                    function_name,
+                    reason,
+                } => write!(
+                    f,
+                    "Failed to call the `{}` exported function of instance `{}`: {}",
+                    function_name, instance_id, reason
+                ),
+                WasmError::Trap {
+                    instance_id,
+                    function_name,
+                    source,
+                } => write!(
+                    f,
+                    "Failed to call the `{}` exported function of instance `{}`: {}",
+                    function_name, instance_id, source
+                ),
+                WasmError::MemoryOutOfBounds {
+                    instance_id,
+                    function_name,
+                    ptr,
+                    len,
+                } => write!(
+                    f,
+                    "Failed to call the `{}` exported function of instance `{}`: it described a buffer at offset {} of length {} that falls outside of the instance's memory.",
+                    function_name, instance_id, ptr, len
+                ),
+            }
+        }
+    }
+
+    impl WasmError {
+        /// The packed `errcode()` a SQL caller should see for this error, in
+        /// the form Postgres' own `MAKE_SQLSTATE` macro produces from the
+        /// standard 5-character SQLSTATE.
+        fn sqlstate(&self) -> i32 {
+            match self {
+                WasmError::InstanceNotFound { .. } | WasmError::ExportNotFound { .. } => {
+                    sqlstate("42883") // undefined_function: unknown instance/export ID
+                }
+                WasmError::ArityMismatch { .. }
+                | WasmError::TypeMismatch { .. }
+                | WasmError::MemoryOutOfBounds { .. } => {
+                    sqlstate("22023") // invalid_parameter_value
+                }
+                WasmError::Compile { .. } | WasmError::Instantiate { .. } => {
+                    sqlstate("38000") // external_routine_exception
+                }
+                WasmError::Trap { .. } => sqlstate("39000"), // external_routine_invocation_exception
+                WasmError::FileOpen { .. } | WasmError::FileRead { .. } => sqlstate("58030"), // io_error
+            }
+        }
+    }
+
+    /// Postgres' `PGSIXBIT`: the low 6 bits of a SQLSTATE character, stable
+    /// for both the digits and uppercase letters the error-code table uses.
+    const fn sixbit(ch: u8) -> i32 {
+        ((ch.wrapping_sub(b'0')) & 0x3F) as i32
+    }
+
+    /// Encodes a 5-character SQLSTATE such as `"22023"` the way Postgres'
+    /// own `MAKE_SQLSTATE` macro does, for use with `errcode`.
+    const fn sqlstate(code: &str) -> i32 {
+        let bytes = code.as_bytes();
+
+        sixbit(bytes[0])
+            | (sixbit(bytes[1]) << 6)
+            | (sixbit(bytes[2]) << 12)
+            | (sixbit(bytes[3]) << 18)
+            | (sixbit(bytes[4]) << 24)
+    }
+
+    /// Reports `error` to Postgres at `ERROR` level with its SQLSTATE and
+    /// aborts the current statement. Never returns.
+    ///
+    /// This calls `errstart`/`errcode`/`errmsg`/`errfinish` directly rather
+    /// than going through `pg_extend`'s own `error!` macro, because `error!`
+    /// has no way to attach a real `errcode()` - it always reports under the
+    /// same generic SQLSTATE, which defeats the point of [`WasmError`]
+    /// carrying a distinct one per variant. Raising this way is only safe
+    /// because every call site resolves what it needs from the instance
+    /// registry and drops the `RwLockReadGuard` *before* calling `raise`
+    /// (see `lookup_function` and `lookup_bytes_exports`) - a raw
+    /// `errstart`/`errfinish` longjmps straight past any Rust guard still on
+    /// the stack without running its `Drop`.
+    pub(super) fn raise(error: WasmError) -> ! {
+        let code = error.sqlstate();
+        let message = error.to_string();
+
+        unsafe {
+            if pg_sys::errstart(pg_sys::ERROR as i32, std::ptr::null()) {
+                pg_sys::errcode(code);
+
+                let c_message = std::ffi::CString::new(message).unwrap_or_else(|_| {
+                    std::ffi::CString::new(
+                        "wasmer-postgres: error message contained a NUL byte",
+                    )
+                    .unwrap()
+                });
+                pg_sys::errmsg(c_message.as_ptr());
+            }
+
+            // PG13+'s `errfinish` takes the call site instead of `errstart`
+            // taking it, unlike the pre-13 `errstart(elevel, filename,
+            // lineno, funcname, domain)` / `errfinish(dummy)` split.
+            pg_sys::errfinish(
+                concat!(file!(), "\0").as_ptr() as *const std::os::raw::c_char,
+                line!() as i32,
+                b"raise\0".as_ptr() as *const std::os::raw::c_char,
+            );
+        }
+
+        unreachable!("`errfinish` at ERROR level never returns")
+    }
+}
+
+use error::{raise, WasmError};
+
+mod cache {
+    //! Filesystem-backed cache of compiled [`Module`]s, keyed by the content
+    //! hash of the `.wasm` bytes (the same hash `new_instance` derives its
+    //! instance ID from), so that instantiating the same file again - in
+    //! this session, in another backend, or after a restart - skips
+    //! recompiling it from scratch.
+    //!
+    //! The cache directory defaults to `/tmp/wasmer-postgres-cache` and can
+    //! be overridden by setting the `WASMER_POSTGRES_CACHE_DIR` environment
+    //! variable before the backend starts (until this extension grows a
+    //! proper GUC for it).
+
+    use pg_extend::error;
+    use std::{fs, path::PathBuf};
+    use wasmer::{Module, Store};
+    use wasmer_cache::{Cache, FileSystemCache, Hash};
+
+    fn directory() -> PathBuf {
+        std::env::var("WASMER_POSTGRES_CACHE_DIR")
+            .unwrap_or_else(|_| "/tmp/wasmer-postgres-cache".to_owned())
+            .into()
+    }
+
+    fn open() -> Option<FileSystemCache> {
+        let directory = directory();
+
+        if let Err(e) = fs::create_dir_all(&directory) {
+            error!(
+                "error creating module cache directory {:?}: {}",
+                directory, e
+            );
+
+            return None;
+        }
+
+        match FileSystemCache::new(directory.clone()) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                error!("error opening module cache directory {:?}: {}", directory, e);
+
+                None
+            }
+        }
+    }
+
+    /// Looks up the module compiled from `bytes` in the cache, returning its
+    /// content hash alongside the deserialized module when present.
+    pub(super) fn load(store: &Store, bytes: &[u8]) -> (Hash, Option<Module>) {
+        let hash = Hash::generate(bytes);
+        let module = open().and_then(|cache| cache.load(store, hash).ok());
+
+        (hash, module)
+    }
+
+    /// Serializes `module` under `hash` so future lookups can skip
+    /// recompilation.
+    pub(super) fn store(hash: Hash, module: &Module) {
+        if let Some(mut cache) = open() {
+            if let Err(e) = cache.store(hash, module) {
+                error!("error storing compiled module {} in cache: {}", hash, e);
+            }
+        }
+    }
+
+    /// Lists the content hashes of every module currently on disk in the
+    /// cache directory.
+    pub(super) fn list() -> Vec<String> {
+        fs::read_dir(directory())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect()
+    }
+
+    /// Removes a single cached module by its content hash. Returns whether
+    /// an entry was actually removed.
+    pub(super) fn evict(key: &str) -> bool {
+        fs::remove_file(directory().join(key)).is_ok()
+    }
+}
 
 pub(crate) struct InstanceInfo {
     pub(crate) instance: Instance,
@@ -22,320 +427,630 @@ pub(crate) fn get_instances() -> &'static RwLock<HashMap<String, InstanceInfo>>
     }
 }
 
-#[pg_extern]
-fn new_instance(wasm_file: String) -> Option<String> {
-    let mut file = match File::open(&wasm_file) {
+/// Reads, compiles (or loads from [`cache`]), and instantiates the module at
+/// `wasm_file`, along with the content hash it was compiled under. Shared by
+/// `new_instance` (which mints a fresh instance ID from the hash) and
+/// `reload_instance` (which re-instantiates in place, keeping the ID).
+fn compile_and_instantiate(wasm_file: &str) -> (Instance, wasmer_cache::Hash) {
+    let mut file = match File::open(wasm_file) {
         Ok(file) => file,
-        Err(e) => {
-            error!("error opening {} - {}", &wasm_file, e);
-            return None
-        },
+        Err(source) => raise(WasmError::FileOpen {
+            wasm_file: wasm_file.to_owned(),
+            source,
+        }),
     };
 
-    debug!("opened WASM file {}", &wasm_file);
+    debug!("opened WASM file {}", wasm_file);
 
     let mut bytes = Vec::new();
 
-    if let Err(e) = file.read_to_end(&mut bytes) {
-        error!("error reading {} - {}", &wasm_file, e);
-        return None;
+    if let Err(source) = file.read_to_end(&mut bytes) {
+        raise(WasmError::FileRead {
+            wasm_file: wasm_file.to_owned(),
+            source,
+        });
     }
 
-    debug!("read WASM file {}", &wasm_file);
+    debug!("read WASM file {}", wasm_file);
 
     let store = Store::default();
-    let module = Module::new(&store, &bytes).unwrap();
+    let (hash, cached_module) = cache::load(&store, &bytes);
+    let module = match cached_module {
+        Some(module) => {
+            debug!("loaded cached module for WASM file {}", wasm_file);
+            module
+        }
+        None => {
+            let module = match Module::new(&store, &bytes) {
+                Ok(module) => module,
+                Err(source) => raise(WasmError::Compile {
+                    wasm_file: wasm_file.to_owned(),
+                    source: source.to_string(),
+                }),
+            };
+
+            debug!("created module for WASM file {}", wasm_file);
 
-    debug!("created module for WASM file {}", &wasm_file);
+            cache::store(hash, &module);
+            module
+        }
+    };
 
-    let import_object = imports! {};
+    let import_object = host::import_object(&store);
     match Instance::new(&module, &import_object) {
-        Ok(instance) => {
-            let mut instances = get_instances().write().unwrap();
-            let key = Uuid::new_v5(
-                &Uuid::NAMESPACE_OID,
-                wasmer_cache::Hash::generate(bytes.as_slice()).to_string().as_bytes()
-            )
-            .to_hyphenated()
-            .to_string();
-
-            debug!("adding instance with key {}", &key);
-
-            instances.insert(
-                key.clone(),
-                InstanceInfo {
-                    instance,
-                    wasm_file,
-                },
-            );
+        Ok(instance) => (instance, hash),
+        Err(source) => raise(WasmError::Instantiate {
+            wasm_file: wasm_file.to_owned(),
+            source: source.to_string(),
+        }),
+    }
+}
 
-            Some(key)
-        },
-        Err(e) => {
-            error!("error instantiating instance from {} - {}", &wasm_file, e);
-            None
+#[pg_extern]
+fn new_instance(wasm_file: String) -> String {
+    let (instance, hash) = compile_and_instantiate(&wasm_file);
+    let mut instances = get_instances().write().unwrap();
+    let key = Uuid::new_v5(&Uuid::NAMESPACE_OID, hash.to_string().as_bytes())
+        .to_hyphenated()
+        .to_string();
+
+    debug!("adding instance with key {}", &key);
+
+    instances.insert(
+        key.clone(),
+        InstanceInfo {
+            instance,
+            wasm_file,
         },
+    );
+
+    key
+}
+
+#[pg_extern]
+fn list_cached_modules() -> Vec<String> {
+    cache::list()
+}
+
+#[pg_extern]
+fn evict_cached_module(key: String) -> bool {
+    cache::evict(&key)
+}
+
+#[pg_extern]
+fn drop_instance(instance_id: String) -> bool {
+    let removed = get_instances().write().unwrap().remove(&instance_id).is_some();
+
+    if removed {
+        debug!("dropped instance with key {}", &instance_id);
     }
+
+    removed
 }
 
-fn invoke_function(instance_id: String, function_name: String, arguments: &[i64]) -> Option<i64> {
-    let instances = get_instances().read().unwrap();
+#[pg_extern]
+fn drop_all_instances() {
+    get_instances().write().unwrap().clear();
+    debug!("dropped all instances");
+}
 
-    match instances.get(&instance_id) {
-        Some(InstanceInfo { instance, .. }) => {
-            let function = match instance.exports.get_function(&function_name) {
-                Ok(function) => function,
-                Err(error) => {
-                    error!(
-                        "Exported function `{}` does not exist in instance `{}`: {}",
-                        function_name, instance_id, error
-                    );
-
-                    return None;
-                }
-            };
+#[pg_extern]
+fn reload_instance(instance_id: String) -> bool {
+    let wasm_file = {
+        let instances = get_instances().read().unwrap();
 
-            let signature = function.ty();
-            let parameters = signature.params();
-            let number_of_parameters = parameters.len() as isize;
-            let number_of_arguments = arguments.len() as isize;
-            let diff: isize = number_of_parameters - number_of_arguments;
+        match instances.get(&instance_id) {
+            Some(instance_info) => instance_info.wasm_file.clone(),
+            None => return false,
+        }
+    };
 
-            if diff != 0 {
-                error!(
-                    "Failed to call the `{}` exported function of instance `{}`: Invalid number of arguments.",
-                    function_name, instance_id
-                );
+    let (instance, _hash) = compile_and_instantiate(&wasm_file);
+    let mut instances = get_instances().write().unwrap();
 
-                return None;
-            }
+    match instances.get_mut(&instance_id) {
+        Some(instance_info) => {
+            instance_info.instance = instance;
+            debug!("reloaded instance with key {}", &instance_id);
 
-            let mut function_arguments = Vec::<Value>::with_capacity(number_of_parameters as usize);
+            true
+        }
+        None => false,
+    }
+}
 
-            for (parameter, argument) in parameters.iter().zip(arguments.iter()) {
-                let value = match parameter {
-                    Type::I32 => Value::I32(*argument as i32),
-                    Type::I64 => Value::I64(*argument),
-                    _ => {
-                        error!(
-                            "Failed to call the `{}` exported function of instance `{}`: Cannot call it because one of its argument expect a float (`f32` or `f64`), and it is not supported yet by the Postgres extension.",
-                            function_name, instance_id
-                        );
+/// A scalar argument coming in from SQL, still tagged with the Postgres type
+/// it was passed as, so it can be checked against the WASM signature before
+/// conversion.
+#[derive(Clone, Copy)]
+enum Argument {
+    I64(i64),
+    F64(f64),
+}
 
-                        return None;
-                    }
-                };
+/// Looks up `function_name` in `instance_id`'s exports, cloning it (`wasmer`
+/// functions are cheap `Arc`-backed handles) so the caller gets an owned
+/// value back instead of one borrowed from the registry's `RwLockReadGuard`.
+/// This guarantees the guard - a local to this function - is dropped before
+/// the caller can possibly call [`raise`] on the result, so a `SELECT`
+/// that traps/mismatches can never leave the instance registry's read lock
+/// held past the end of the statement.
+fn lookup_function(instance_id: &str, function_name: &str) -> Result<wasmer::Function, WasmError> {
+    let instances = get_instances().read().unwrap();
 
-                function_arguments.push(value);
-            }
+    let instance_info = instances
+        .get(instance_id)
+        .ok_or_else(|| WasmError::InstanceNotFound {
+            instance_id: instance_id.to_owned(),
+        })?;
 
-            let results = match function.call(function_arguments.as_slice()) {
-                Ok(results) => results,
-                Err(error) => {
-                    error!(
-                        "Failed to call the `{}` exported function of instance `{}`: {}",
-                        function_name, instance_id, error
-                    );
+    instance_info
+        .instance
+        .exports
+        .get_function(function_name)
+        .map(Clone::clone)
+        .map_err(|source| WasmError::ExportNotFound {
+            instance_id: instance_id.to_owned(),
+            name: function_name.to_owned(),
+            source: source.to_string(),
+        })
+}
 
-                    return None;
-                }
-            };
+/// Calls an exported function with the given signature-tagged `arguments`
+/// and returns every value it produced, in order. Callers that expect a
+/// single scalar result should pass the outcome through [`single_result`].
+fn call_function(instance_id: String, function_name: String, arguments: &[Argument]) -> Vec<Value> {
+    let function = match lookup_function(&instance_id, &function_name) {
+        Ok(function) => function,
+        Err(error) => raise(error),
+    };
 
-            if results.len() == 1 {
-                match results[0] {
-                    Value::I32(value) => Some(value as i64),
-                    Value::I64(value) => Some(value),
-                    _ => None,
-                }
-            } else {
-                None
+    let signature = function.ty();
+    let parameters = signature.params();
+    let number_of_parameters = parameters.len() as isize;
+    let number_of_arguments = arguments.len() as isize;
+    let diff: isize = number_of_parameters - number_of_arguments;
+
+    if diff != 0 {
+        raise(WasmError::ArityMismatch {
+            instance_id,
+            function_name,
+        });
+    }
+
+    let mut function_arguments = Vec::<Value>::with_capacity(number_of_parameters as usize);
+
+    for (parameter, argument) in parameters.iter().zip(arguments.iter()) {
+        let value = match (parameter, argument) {
+            (Type::I32, Argument::I64(value)) => Value::I32(*value as i32),
+            (Type::I64, Argument::I64(value)) => Value::I64(*value),
+            (Type::F32, Argument::F64(value)) => Value::F32(*value as f32),
+            (Type::F64, Argument::F64(value)) => Value::F64(*value),
+            (Type::I32, Argument::F64(_)) | (Type::I64, Argument::F64(_)) => {
+                raise(WasmError::TypeMismatch {
+                    instance_id,
+                    function_name,
+                    reason: "One of its arguments expects an integer (`i32` or `i64`), but a float was given.".to_owned(),
+                });
             }
-        }
+            (Type::F32, Argument::I64(_)) | (Type::F64, Argument::I64(_)) => {
+                raise(WasmError::TypeMismatch {
+                    instance_id,
+                    function_name,
+                    reason: "One of its arguments expects a float (`f32` or `f64`), but an integer was given.".to_owned(),
+                });
+            }
+            _ => raise(WasmError::TypeMismatch {
+                instance_id,
+                function_name,
+                reason: "Cannot call it because one of its arguments has a type that is not supported yet by the Postgres extension.".to_owned(),
+            }),
+        };
 
-        None => {
-            error!("Instance with ID `{}` isn't found.", instance_id);
+        function_arguments.push(value);
+    }
 
-            None
-        }
+    match function.call(function_arguments.as_slice()) {
+        Ok(results) => results.iter().cloned().collect(),
+        Err(source) => raise(WasmError::Trap {
+            instance_id,
+            function_name,
+            source: source.to_string(),
+        }),
     }
 }
 
-#[pg_extern]
-fn invoke_function_0(instance_id: String, function_name: String) -> Option<i64> {
-    invoke_function(instance_id, function_name, &[])
+/// Unwraps a single-value result, raising if the function returned zero or
+/// more than one value.
+fn single_result(mut results: Vec<Value>, instance_id: &str, function_name: &str) -> Value {
+    if results.len() == 1 {
+        results.remove(0)
+    } else {
+        raise(WasmError::TypeMismatch {
+            instance_id: instance_id.to_owned(),
+            function_name: function_name.to_owned(),
+            reason: "It does not return exactly one value; use `invoke_function_set` for multi-value results.".to_owned(),
+        })
+    }
 }
 
-#[pg_extern]
-fn invoke_function_1(instance_id: String, function_name: String, argument0: i64) -> Option<i64> {
-    invoke_function(instance_id, function_name, &[argument0])
+fn result_to_i64(result: Value, function_name: &str, instance_id: &str) -> i64 {
+    match result {
+        Value::I32(value) => value as i64,
+        Value::I64(value) => value,
+        _ => raise(WasmError::TypeMismatch {
+            instance_id: instance_id.to_owned(),
+            function_name: function_name.to_owned(),
+            reason: "It returns a float (`f32` or `f64`), use `invoke_function_f` or `invoke_function_mixed_f` instead.".to_owned(),
+        }),
+    }
 }
 
-#[pg_extern]
-fn invoke_function_2(
-    instance_id: String,
-    function_name: String,
-    argument0: i64,
-    argument1: i64,
-) -> Option<i64> {
-    invoke_function(instance_id, function_name, &[argument0, argument1])
+fn result_to_f64(result: Value, function_name: &str, instance_id: &str) -> f64 {
+    match result {
+        Value::F32(value) => value as f64,
+        Value::F64(value) => value,
+        _ => raise(WasmError::TypeMismatch {
+            instance_id: instance_id.to_owned(),
+            function_name: function_name.to_owned(),
+            reason: "It returns an integer (`i32` or `i64`), use `invoke_function` or `invoke_function_mixed` instead.".to_owned(),
+        }),
+    }
 }
 
 #[pg_extern]
-fn invoke_function_3(
-    instance_id: String,
-    function_name: String,
-    argument0: i64,
-    argument1: i64,
-    argument2: i64,
-) -> Option<i64> {
-    invoke_function(
-        instance_id,
-        function_name,
-        &[argument0, argument1, argument2],
-    )
+fn invoke_function(instance_id: String, function_name: String, arguments: Vec<i64>) -> i64 {
+    let call_arguments: Vec<Argument> = arguments.into_iter().map(Argument::I64).collect();
+    let results = call_function(instance_id.clone(), function_name.clone(), &call_arguments);
+    let result = single_result(results, &instance_id, &function_name);
+
+    result_to_i64(result, &function_name, &instance_id)
 }
 
 #[pg_extern]
-fn invoke_function_4(
-    instance_id: String,
-    function_name: String,
-    argument0: i64,
-    argument1: i64,
-    argument2: i64,
-    argument3: i64,
-) -> Option<i64> {
-    invoke_function(
-        instance_id,
-        function_name,
-        &[argument0, argument1, argument2, argument3],
-    )
+fn invoke_function_f(instance_id: String, function_name: String, arguments: Vec<f64>) -> f64 {
+    let call_arguments: Vec<Argument> = arguments.into_iter().map(Argument::F64).collect();
+    let results = call_function(instance_id.clone(), function_name.clone(), &call_arguments);
+    let result = single_result(results, &instance_id, &function_name);
+
+    result_to_f64(result, &function_name, &instance_id)
 }
 
-#[pg_extern]
-fn invoke_function_5(
-    instance_id: String,
-    function_name: String,
-    argument0: i64,
-    argument1: i64,
-    argument2: i64,
-    argument3: i64,
-    argument4: i64,
-) -> Option<i64> {
-    invoke_function(
-        instance_id,
-        function_name,
-        &[argument0, argument1, argument2, argument3, argument4],
-    )
+/// Builds a mixed-type argument list from two homogeneous arrays plus an
+/// `is_float` flag per position, so functions like `fn(i64, f64) -> f64`
+/// become callable: `invoke_function`/`invoke_function_f` can only reach
+/// signatures whose parameters are all integers or all floats, even though
+/// `Argument` itself can tag each position independently. `int_arguments`
+/// and `float_arguments` are consumed in order for the positions where
+/// `is_float` is `false`/`true` respectively; together they must supply
+/// exactly `is_float.len()` values or this is an arity error.
+fn mixed_arguments(
+    instance_id: &str,
+    function_name: &str,
+    is_float: Vec<bool>,
+    int_arguments: Vec<i64>,
+    float_arguments: Vec<f64>,
+) -> Vec<Argument> {
+    let expected_floats = is_float.iter().filter(|is_float| **is_float).count();
+    let expected_ints = is_float.len() - expected_floats;
+
+    if int_arguments.len() != expected_ints || float_arguments.len() != expected_floats {
+        raise(WasmError::ArityMismatch {
+            instance_id: instance_id.to_owned(),
+            function_name: function_name.to_owned(),
+        });
+    }
+
+    let mut ints = int_arguments.into_iter();
+    let mut floats = float_arguments.into_iter();
+
+    is_float
+        .into_iter()
+        .map(|is_float| {
+            if is_float {
+                Argument::F64(floats.next().expect("count checked above"))
+            } else {
+                Argument::I64(ints.next().expect("count checked above"))
+            }
+        })
+        .collect()
 }
 
+/// Calls an exported function whose signature mixes integer and float
+/// parameters, returning an integer result. `is_float[i]` says whether
+/// position `i` is drawn from `float_arguments` or `int_arguments`; see
+/// [`mixed_arguments`].
 #[pg_extern]
-fn invoke_function_6(
+fn invoke_function_mixed(
     instance_id: String,
     function_name: String,
-    argument0: i64,
-    argument1: i64,
-    argument2: i64,
-    argument3: i64,
-    argument4: i64,
-    argument5: i64,
-) -> Option<i64> {
-    invoke_function(
-        instance_id,
-        function_name,
-        &[
-            argument0, argument1, argument2, argument3, argument4, argument5,
-        ],
-    )
+    is_float: Vec<bool>,
+    int_arguments: Vec<i64>,
+    float_arguments: Vec<f64>,
+) -> i64 {
+    let call_arguments = mixed_arguments(&instance_id, &function_name, is_float, int_arguments, float_arguments);
+    let results = call_function(instance_id.clone(), function_name.clone(), &call_arguments);
+    let result = single_result(results, &instance_id, &function_name);
+
+    result_to_i64(result, &function_name, &instance_id)
 }
 
+/// Same as [`invoke_function_mixed`], for functions whose mixed signature
+/// returns a float.
 #[pg_extern]
-fn invoke_function_7(
+fn invoke_function_mixed_f(
     instance_id: String,
     function_name: String,
-    argument0: i64,
-    argument1: i64,
-    argument2: i64,
-    argument3: i64,
-    argument4: i64,
-    argument5: i64,
-    argument6: i64,
-) -> Option<i64> {
-    invoke_function(
-        instance_id,
-        function_name,
-        &[
-            argument0, argument1, argument2, argument3, argument4, argument5, argument6,
-        ],
-    )
+    is_float: Vec<bool>,
+    int_arguments: Vec<i64>,
+    float_arguments: Vec<f64>,
+) -> f64 {
+    let call_arguments = mixed_arguments(&instance_id, &function_name, is_float, int_arguments, float_arguments);
+    let results = call_function(instance_id.clone(), function_name.clone(), &call_arguments);
+    let result = single_result(results, &instance_id, &function_name);
+
+    result_to_f64(result, &function_name, &instance_id)
 }
 
+/// Calls a WASM function that returns more than one value and emits one row
+/// per result value (`RETURNS SETOF int8`), so multi-return functions don't
+/// have to be discarded the way `invoke_function`/`invoke_function_f`
+/// (which require exactly one result) would discard them. Returning `impl
+/// Iterator` rather than `Vec<i64>` is what makes this a set-returning
+/// function instead of one row holding an `int8[]`.
+///
+/// This deliberately stays a `#[pg_extern]` SRF rather than another
+/// `ForeignData`/`ForeignRow` wrapper like `instances`/`exported_functions`:
+/// those expose the whole instance registry as a standing table with no
+/// per-call input, which is exactly what a foreign table models. This is
+/// the opposite shape - a function called with fresh `instance_id`/
+/// `function_name`/`arguments` on every invocation - and `ForeignData::begin`
+/// only ever sees the server/table's static `OPTIONS`, not per-call
+/// arguments, so it can't express this at all.
 #[pg_extern]
-fn invoke_function_8(
+fn invoke_function_set(
     instance_id: String,
     function_name: String,
-    argument0: i64,
-    argument1: i64,
-    argument2: i64,
-    argument3: i64,
-    argument4: i64,
-    argument5: i64,
-    argument6: i64,
-    argument7: i64,
-) -> Option<i64> {
-    invoke_function(
-        instance_id,
-        function_name,
-        &[
-            argument0, argument1, argument2, argument3, argument4, argument5, argument6, argument7,
-        ],
-    )
+    arguments: Vec<i64>,
+) -> impl Iterator<Item = i64> {
+    let call_arguments: Vec<Argument> = arguments.into_iter().map(Argument::I64).collect();
+    let results = call_function(instance_id.clone(), function_name.clone(), &call_arguments);
+
+    results
+        .into_iter()
+        .map(move |result| result_to_i64(result, &function_name, &instance_id))
+}
+
+/// Validates that a guest-supplied `(ptr, len)` pair describes a range that
+/// actually falls inside `memory`, raising [`WasmError::MemoryOutOfBounds`]
+/// otherwise. A buggy or hostile guest export can report any `i32`s it
+/// likes here, so every access through [`write_guest_bytes`],
+/// [`read_guest_bytes`], and [`read_guest_i32`] is checked through this
+/// before it touches the view, instead of trusting the guest and indexing
+/// straight into it.
+fn checked_guest_range(
+    memory: &wasmer::Memory,
+    ptr: i32,
+    len: i32,
+    instance_id: &str,
+    function_name: &str,
+) -> std::ops::Range<usize> {
+    let out_of_bounds = || {
+        raise(WasmError::MemoryOutOfBounds {
+            instance_id: instance_id.to_owned(),
+            function_name: function_name.to_owned(),
+            ptr,
+            len,
+        })
+    };
+
+    if ptr < 0 || len < 0 {
+        out_of_bounds();
+    }
+
+    let start = ptr as usize;
+    let end = match start.checked_add(len as usize) {
+        Some(end) => end,
+        None => out_of_bounds(),
+    };
+
+    if end > memory.view::<u8>().len() {
+        out_of_bounds();
+    }
+
+    start..end
+}
+
+/// Writes `bytes` into the instance's linear memory starting at `ptr`.
+fn write_guest_bytes(
+    memory: &wasmer::Memory,
+    ptr: i32,
+    bytes: &[u8],
+    instance_id: &str,
+    function_name: &str,
+) {
+    let range = checked_guest_range(memory, ptr, bytes.len() as i32, instance_id, function_name);
+    let view = memory.view::<u8>();
+
+    for (cell, byte) in view[range].iter().zip(bytes.iter()) {
+        cell.set(*byte);
+    }
+}
+
+/// Reads `len` bytes out of the instance's linear memory starting at `ptr`.
+fn read_guest_bytes(
+    memory: &wasmer::Memory,
+    ptr: i32,
+    len: i32,
+    instance_id: &str,
+    function_name: &str,
+) -> Vec<u8> {
+    let range = checked_guest_range(memory, ptr, len, instance_id, function_name);
+    let view = memory.view::<u8>();
+
+    view[range].iter().map(|cell| cell.get()).collect()
+}
+
+/// Reads a little-endian `i32` out of the instance's linear memory at `ptr`,
+/// used to decode the `(ptr, len)` pair a guest writes to describe its
+/// returned `text`/`bytea` value.
+fn read_guest_i32(memory: &wasmer::Memory, ptr: i32, instance_id: &str, function_name: &str) -> i32 {
+    let bytes = read_guest_bytes(memory, ptr, 4, instance_id, function_name);
+
+    i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// The exports `invoke_function_bytes` needs out of an instance, cloned out
+/// from under the instance registry's `RwLockReadGuard` (see
+/// [`lookup_function`] for why) so the guard is dropped before any of them
+/// are called or a lookup failure is raised.
+struct BytesExports {
+    memory: wasmer::Memory,
+    alloc: wasmer::Function,
+    function: wasmer::Function,
+    dealloc: Option<wasmer::Function>,
+}
+
+fn lookup_bytes_exports(instance_id: &str, function_name: &str) -> Result<BytesExports, WasmError> {
+    let instances = get_instances().read().unwrap();
+
+    let instance_info = instances
+        .get(instance_id)
+        .ok_or_else(|| WasmError::InstanceNotFound {
+            instance_id: instance_id.to_owned(),
+        })?;
+
+    let exports = &instance_info.instance.exports;
+
+    let memory = exports
+        .get_memory("memory")
+        .map(Clone::clone)
+        .map_err(|source| WasmError::ExportNotFound {
+            instance_id: instance_id.to_owned(),
+            name: "memory".to_owned(),
+            source: source.to_string(),
+        })?;
+
+    let alloc = exports
+        .get_function("alloc")
+        .map(Clone::clone)
+        .map_err(|source| WasmError::ExportNotFound {
+            instance_id: instance_id.to_owned(),
+            name: "alloc".to_owned(),
+            source: source.to_string(),
+        })?;
+
+    let function = exports
+        .get_function(function_name)
+        .map(Clone::clone)
+        .map_err(|source| WasmError::ExportNotFound {
+            instance_id: instance_id.to_owned(),
+            name: function_name.to_owned(),
+            source: source.to_string(),
+        })?;
+
+    let dealloc = exports.get_function("dealloc").map(Clone::clone).ok();
+
+    Ok(BytesExports {
+        memory,
+        alloc,
+        function,
+        dealloc,
+    })
+}
+
+/// Calls a `text`/`bytea`-accepting, `text`/`bytea`-returning exported
+/// function.
+///
+/// ABI: the module must export linear `memory` plus an `alloc(len: i32) ->
+/// i32` function (and may export `dealloc(ptr: i32, len: i32)`, called on
+/// every buffer once we're done with it). The argument's bytes are copied
+/// into a guest buffer obtained from `alloc`, and `(ptr, len)` is passed to
+/// the target function as two `i32`s. The function is expected to return a
+/// pointer to an 8-byte `(ptr: i32, len: i32)` pair, written in guest
+/// memory, describing its result buffer; that pair and the buffer it points
+/// to are read back and turned into the returned `Vec<u8>`.
+fn invoke_function_bytes(instance_id: String, function_name: String, argument: &[u8]) -> Vec<u8> {
+    let BytesExports {
+        memory,
+        alloc,
+        function,
+        dealloc,
+    } = match lookup_bytes_exports(&instance_id, &function_name) {
+        Ok(exports) => exports,
+        Err(error) => raise(error),
+    };
+    let memory = &memory;
+
+    let argument_ptr = match alloc.call(&[Value::I32(argument.len() as i32)]) {
+        Ok(results) => match results.get(0) {
+            Some(Value::I32(ptr)) => *ptr,
+            _ => raise(WasmError::TypeMismatch {
+                instance_id,
+                function_name: "alloc".to_owned(),
+                reason: "It did not return an `i32` pointer.".to_owned(),
+            }),
+        },
+        Err(source) => raise(WasmError::Trap {
+            instance_id,
+            function_name: "alloc".to_owned(),
+            source: source.to_string(),
+        }),
+    };
+
+    write_guest_bytes(memory, argument_ptr, argument, &instance_id, &function_name);
+
+    let results = match function.call(&[
+        Value::I32(argument_ptr),
+        Value::I32(argument.len() as i32),
+    ]) {
+        Ok(results) => results,
+        Err(source) => raise(WasmError::Trap {
+            instance_id,
+            function_name,
+            source: source.to_string(),
+        }),
+    };
+
+    let out_ptr = match results.get(0) {
+        Some(Value::I32(ptr)) => *ptr,
+        _ => raise(WasmError::TypeMismatch {
+            instance_id,
+            function_name,
+            reason: "It did not return a pointer to its `(ptr, len)` result pair.".to_owned(),
+        }),
+    };
+
+    let result_ptr = read_guest_i32(memory, out_ptr, &instance_id, &function_name);
+    let result_len = read_guest_i32(memory, out_ptr + 4, &instance_id, &function_name);
+    let result = read_guest_bytes(memory, result_ptr, result_len, &instance_id, &function_name);
+
+    if let Some(dealloc) = dealloc {
+        let _ = dealloc.call(&[Value::I32(argument_ptr), Value::I32(argument.len() as i32)]);
+        let _ = dealloc.call(&[Value::I32(result_ptr), Value::I32(result_len)]);
+    }
+
+    result
 }
 
 #[pg_extern]
-fn invoke_function_9(
-    instance_id: String,
-    function_name: String,
-    argument0: i64,
-    argument1: i64,
-    argument2: i64,
-    argument3: i64,
-    argument4: i64,
-    argument5: i64,
-    argument6: i64,
-    argument7: i64,
-    argument8: i64,
-) -> Option<i64> {
-    invoke_function(
-        instance_id,
-        function_name,
-        &[
-            argument0, argument1, argument2, argument3, argument4, argument5, argument6, argument7,
-            argument8,
-        ],
-    )
+fn invoke_function_text_1(instance_id: String, function_name: String, argument: String) -> String {
+    let bytes = invoke_function_bytes(instance_id.clone(), function_name.clone(), argument.as_bytes());
+
+    String::from_utf8(bytes).unwrap_or_else(|source| {
+        raise(WasmError::TypeMismatch {
+            instance_id,
+            function_name,
+            reason: format!("It returned bytes that aren't valid UTF-8: {}", source),
+        })
+    })
 }
 
 #[pg_extern]
-fn invoke_function_10(
+fn invoke_function_bytea_1(
     instance_id: String,
     function_name: String,
-    argument0: i64,
-    argument1: i64,
-    argument2: i64,
-    argument3: i64,
-    argument4: i64,
-    argument5: i64,
-    argument6: i64,
-    argument7: i64,
-    argument8: i64,
-    argument9: i64,
-) -> Option<i64> {
-    invoke_function(
-        instance_id,
-        function_name,
-        &[
-            argument0, argument1, argument2, argument3, argument4, argument5, argument6, argument7,
-            argument8, argument9,
-        ],
-    )
+    argument: Vec<u8>,
+) -> Vec<u8> {
+    invoke_function_bytes(instance_id, function_name, argument.as_slice())
 }